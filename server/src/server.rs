@@ -1,66 +1,413 @@
-use std::io;
-use std::str;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::sync::RwLock;
+use async_std::task;
+use futures::stream::StreamExt;
 
 use seroost_lib::model::*;
 
-use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use http_types::{Method, Response, StatusCode};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+mod peers;
+
+pub use peers::{FederationConfig, PeerConfig};
+use peers::{merge_peer_hits, query_peers, PeerHit};
 
-fn serve_404(request: Request) -> io::Result<()> {
-    request.respond(Response::from_string("404").with_status_code(StatusCode(404)))
+/// Prometheus metrics shared across all request handlers.
+pub struct Metrics {
+    registry: Registry,
+    requests_total: IntCounterVec,
+    search_query_duration_seconds: HistogramVec,
+    docs_count: IntGauge,
+    terms_count: IntGauge,
 }
 
-fn serve_500(request: Request) -> io::Result<()> {
-    request.respond(Response::from_string("500").with_status_code(StatusCode(500)))
+impl Metrics {
+    pub fn new() -> Metrics {
+        let registry = Registry::new();
+
+        let requests_total = IntCounterVec::new(
+            Opts::new("seroost_requests_total", "Total number of HTTP requests"),
+            &["route", "status"],
+        )
+        .expect("that we didn't put any garbage in the metric options");
+        registry
+            .register(Box::new(requests_total.clone()))
+            .expect("that the metric wasn't already registered");
+
+        let search_query_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "seroost_search_query_duration_seconds",
+                "Latency of model.search_query() calls",
+            ),
+            &["route"],
+        )
+        .expect("that we didn't put any garbage in the metric options");
+        registry
+            .register(Box::new(search_query_duration_seconds.clone()))
+            .expect("that the metric wasn't already registered");
+
+        let docs_count = IntGauge::new("seroost_docs_count", "Number of indexed documents")
+            .expect("that we didn't put any garbage in the metric options");
+        registry
+            .register(Box::new(docs_count.clone()))
+            .expect("that the metric wasn't already registered");
+
+        let terms_count = IntGauge::new("seroost_terms_count", "Number of indexed terms")
+            .expect("that we didn't put any garbage in the metric options");
+        registry
+            .register(Box::new(terms_count.clone()))
+            .expect("that the metric wasn't already registered");
+
+        Metrics {
+            registry,
+            requests_total,
+            search_query_duration_seconds,
+            docs_count,
+            terms_count,
+        }
+    }
+
+    fn observe_request(&self, route: &str, status: u16) {
+        self.requests_total
+            .with_label_values(&[route, &status.to_string()])
+            .inc();
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
 }
 
-fn serve_400(request: Request, message: &str) -> io::Result<()> {
-    request
-        .respond(Response::from_string(format!("400: {message}")).with_status_code(StatusCode(400)))
+#[derive(serde::Serialize)]
+struct ErrorResponse<'a> {
+    error: &'a str,
 }
 
-fn serve_bytes(request: Request, bytes: &[u8], content_type: &str) -> io::Result<()> {
-    let content_type_header = Header::from_bytes("Content-Type", content_type)
-        .expect("That we didn't put any garbage in the headers");
-    request.respond(Response::from_data(bytes).with_header(content_type_header))
+fn json_error(metrics: &Metrics, route: &str, status: StatusCode, message: &str) -> Response {
+    metrics.observe_request(route, status as u16);
+    let mut response = Response::new(status);
+    response.set_content_type(http_types::mime::JSON);
+    response
+        .set_body(
+            serde_json::to_string(&ErrorResponse { error: message })
+                .expect("that ErrorResponse always serializes"),
+        );
+    response
 }
 
-// TODO: the errors of serve_api_search should probably return JSON
-// 'Cause that's what expected from them.
-fn serve_api_search(model: Arc<Mutex<Model>>, mut request: Request) -> io::Result<()> {
-    let mut buf = Vec::new();
-    if let Err(err) = request.as_reader().read_to_end(&mut buf) {
-        eprintln!("ERROR: could not read the body of the request: {err}");
-        return serve_500(request);
+fn serve_404(metrics: &Metrics, route: &str) -> Response {
+    json_error(metrics, route, StatusCode::NotFound, "Not Found")
+}
+
+fn serve_500(metrics: &Metrics, route: &str) -> Response {
+    json_error(
+        metrics,
+        route,
+        StatusCode::InternalServerError,
+        "Internal Server Error",
+    )
+}
+
+fn serve_400(metrics: &Metrics, route: &str, message: &str) -> Response {
+    json_error(metrics, route, StatusCode::BadRequest, message)
+}
+
+fn serve_bytes(metrics: &Metrics, route: &str, bytes: &'static [u8], content_type: &str) -> Response {
+    metrics.observe_request(route, 200);
+    let mut response = Response::new(StatusCode::Ok);
+    response.insert_header("Content-Type", content_type);
+    response.set_body(bytes);
+    response
+}
+
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+const MAX_SEARCH_LIMIT: usize = 100;
+
+#[derive(serde::Deserialize)]
+struct SearchRequest {
+    query: String,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// Resolves a request's `limit`/`offset` to concrete values: `limit`
+/// defaults to [`DEFAULT_SEARCH_LIMIT`] and is clamped to [`MAX_SEARCH_LIMIT`],
+/// `offset` defaults to `0`.
+fn resolve_pagination(limit: Option<usize>, offset: Option<usize>) -> (usize, usize) {
+    let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(MAX_SEARCH_LIMIT);
+    let offset = offset.unwrap_or(0);
+    (limit, offset)
+}
+
+/// Quotes a CSV field per RFC 4180: wraps it in `"..."` and doubles any
+/// embedded quotes whenever it contains a comma, quote, or newline.
+fn csv_quote(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') || field.contains('\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
     }
+}
 
-    let body = match str::from_utf8(&buf) {
-        Ok(body) => body.chars().collect::<Vec<_>>(),
+/// Serializes a page of `(path, score)` hits in whatever format the client's
+/// `Accept` header asked for, returning the body bytes and the content-type
+/// to respond with. Falls back to `application/json` when the header is
+/// absent or not one of the supported formats.
+fn serialize_search_results(
+    accept: Option<&str>,
+    page: &[&(std::path::PathBuf, f32)],
+    total: usize,
+) -> (Vec<u8>, &'static str) {
+    match accept {
+        Some(accept) if accept.contains("text/csv") => {
+            let mut csv = String::from("path,score\n");
+            for (path, score) in page {
+                csv.push_str(&format!(
+                    "{},{score}\n",
+                    csv_quote(&path.display().to_string())
+                ));
+            }
+            (csv.into_bytes(), "text/csv")
+        }
+        Some(accept) if accept.contains("application/x-ndjson") => {
+            let mut ndjson = String::new();
+            for (path, score) in page {
+                ndjson.push_str(&serde_json::json!({"path": path, "score": score}).to_string());
+                ndjson.push('\n');
+            }
+            (ndjson.into_bytes(), "application/x-ndjson")
+        }
+        _ => {
+            let hits: Vec<serde_json::Value> = page
+                .iter()
+                .map(|(path, score)| serde_json::json!({ "path": path, "score": score }))
+                .collect();
+            let json = serde_json::json!({ "results": hits, "total": total }).to_string();
+            (json.into_bytes(), "application/json")
+        }
+    }
+}
+
+async fn serve_api_search(
+    metrics: &Metrics,
+    model: Arc<RwLock<Model>>,
+    mut request: http_types::Request,
+) -> http_types::Result<Response> {
+    let route = "/api/search";
+
+    let accept = request
+        .header("Accept")
+        .map(|values| values.as_str().to_string());
+
+    let buf = match request.body_bytes().await {
+        Ok(buf) => buf,
+        Err(err) => {
+            eprintln!("ERROR: could not read the body of the request: {err}");
+            return Ok(serve_500(metrics, route));
+        }
+    };
+
+    let search_request: SearchRequest = match serde_json::from_slice(&buf) {
+        Ok(search_request) => search_request,
+        Err(err) => {
+            eprintln!("ERROR: could not interpret body as a search request: {err}");
+            return Ok(serve_400(
+                metrics,
+                route,
+                "Body must be a JSON object of the form { \"query\": string, \"limit\": number?, \"offset\": number? }",
+            ));
+        }
+    };
+
+    let (limit, offset) = resolve_pagination(search_request.limit, search_request.offset);
+
+    let query_chars = search_request.query.chars().collect::<Vec<_>>();
+
+    // A search is a read: taking a shared read lock lets concurrent searches
+    // and stats lookups run in parallel instead of serializing behind a mutex.
+    let model = model.read().await;
+    let timer = metrics
+        .search_query_duration_seconds
+        .with_label_values(&[route])
+        .start_timer();
+    let result = model.search_query(&query_chars);
+    timer.observe_duration();
+
+    let page = result.iter().skip(offset).take(limit).collect::<Vec<_>>();
+    let (bytes, content_type) = serialize_search_results(accept.as_deref(), &page, result.len());
+
+    metrics.observe_request(route, 200);
+    let mut response = Response::new(StatusCode::Ok);
+    response.insert_header("Content-Type", content_type);
+    response.set_body(bytes);
+    Ok(response)
+}
+
+/// Fans a query out to every configured peer, merges the peers' normalized
+/// hits with the local model's own (also normalized), and responds with the
+/// combined, deduplicated ranking.
+async fn serve_api_fsearch(
+    metrics: &Metrics,
+    model: Arc<RwLock<Model>>,
+    federation: &FederationConfig,
+    mut request: http_types::Request,
+) -> http_types::Result<Response> {
+    let route = "/api/fsearch";
+
+    let buf = match request.body_bytes().await {
+        Ok(buf) => buf,
+        Err(err) => {
+            eprintln!("ERROR: could not read the body of the request: {err}");
+            return Ok(serve_500(metrics, route));
+        }
+    };
+
+    let search_request: SearchRequest = match serde_json::from_slice(&buf) {
+        Ok(search_request) => search_request,
         Err(err) => {
-            eprintln!("ERROR: could not interpret body as UTF-8 string: {err}");
-            return serve_400(request, "Body must be a valid UTF-8 string");
+            eprintln!("ERROR: could not interpret body as a search request: {err}");
+            return Ok(serve_400(
+                metrics,
+                route,
+                "Body must be a JSON object of the form { \"query\": string, \"limit\": number?, \"offset\": number? }",
+            ));
         }
     };
 
-    let model = model.lock().unwrap();
-    let result = model.search_query(&body);
+    let (limit, offset) = resolve_pagination(search_request.limit, search_request.offset);
+    // Fetch (and merge) `offset + limit` candidates so offset can be applied
+    // to the merged, deduplicated ranking rather than being silently dropped.
+    let fetch_limit = offset + limit;
 
-    let json = match serde_json::to_string(&result.iter().take(20).collect::<Vec<_>>()) {
+    let query_chars = search_request.query.chars().collect::<Vec<_>>();
+
+    let local_hits = {
+        let model = model.read().await;
+        let timer = metrics
+            .search_query_duration_seconds
+            .with_label_values(&[route])
+            .start_timer();
+        let result = model.search_query(&query_chars);
+        timer.observe_duration();
+
+        let top_score = result.iter().map(|(_, score)| *score).fold(0.0f32, f32::max);
+        result
+            .into_iter()
+            .map(|(path, score)| PeerHit {
+                path: path.display().to_string(),
+                score: if top_score > 0.0 { score / top_score } else { 0.0 },
+                node: "local".to_string(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut all_hits = local_hits;
+    all_hits.extend(query_peers(&federation.peers, &search_request.query, fetch_limit).await);
+
+    let merged = merge_peer_hits(all_hits, fetch_limit);
+    let page = merged.into_iter().skip(offset).take(limit).collect::<Vec<_>>();
+
+    let json = match serde_json::to_string(&serde_json::json!({ "results": page })) {
         Ok(json) => json,
         Err(err) => {
-            eprintln!("ERROR: could not convert search results to JSON: {err}");
-            return serve_500(request);
+            eprintln!("ERROR: could not convert federated search results to JSON: {err}");
+            return Ok(serve_500(metrics, route));
         }
     };
 
-    let content_type_header = Header::from_bytes("Content-Type", "application/json")
-        .expect("That we didn't put any garbage in the headers");
-    request.respond(Response::from_string(json).with_header(content_type_header))
+    metrics.observe_request(route, 200);
+    let mut response = Response::new(StatusCode::Ok);
+    response.set_content_type(http_types::mime::JSON);
+    response.set_body(json);
+    Ok(response)
 }
 
-fn serve_api_stats(model: Arc<Mutex<Model>>, request: Request) -> io::Result<()> {
+const MAX_BATCH_SIZE: usize = 20;
+
+/// Runs a batch of queries against a single model lock acquisition so that
+/// callers (e.g. type-ahead suggestions) don't pay a connection and a lock
+/// per keystroke.
+async fn serve_api_batch_search(
+    metrics: &Metrics,
+    model: Arc<RwLock<Model>>,
+    mut request: http_types::Request,
+) -> http_types::Result<Response> {
+    let route = "/api/batch-search";
+
+    let buf = match request.body_bytes().await {
+        Ok(buf) => buf,
+        Err(err) => {
+            eprintln!("ERROR: could not read the body of the request: {err}");
+            return Ok(serve_500(metrics, route));
+        }
+    };
+
+    let batch_request: Vec<SearchRequest> = match serde_json::from_slice(&buf) {
+        Ok(batch_request) => batch_request,
+        Err(err) => {
+            eprintln!("ERROR: could not interpret body as a batch search request: {err}");
+            return Ok(serve_400(
+                metrics,
+                route,
+                "Body must be a JSON array of { \"query\": string, \"limit\": number?, \"offset\": number? } objects",
+            ));
+        }
+    };
+
+    if batch_request.len() > MAX_BATCH_SIZE {
+        return Ok(serve_400(
+            metrics,
+            route,
+            &format!("Batch cannot contain more than {MAX_BATCH_SIZE} queries"),
+        ));
+    }
+
+    let model = model.read().await;
+    let responses = batch_request
+        .iter()
+        .map(|search_request| {
+            let (limit, offset) = resolve_pagination(search_request.limit, search_request.offset);
+            let query_chars = search_request.query.chars().collect::<Vec<_>>();
+
+            let timer = metrics
+                .search_query_duration_seconds
+                .with_label_values(&[route])
+                .start_timer();
+            let result = model.search_query(&query_chars);
+            timer.observe_duration();
+
+            let page = result.iter().skip(offset).take(limit).collect::<Vec<_>>();
+            serde_json::json!({ "query": search_request.query, "results": page })
+        })
+        .collect::<Vec<_>>();
+
+    let json = match serde_json::to_string(&responses) {
+        Ok(json) => json,
+        Err(err) => {
+            eprintln!("ERROR: could not convert batch search results to JSON: {err}");
+            return Ok(serve_500(metrics, route));
+        }
+    };
+
+    metrics.observe_request(route, 200);
+    let mut response = Response::new(StatusCode::Ok);
+    response.set_content_type(http_types::mime::JSON);
+    response.set_body(json);
+    Ok(response)
+}
+
+async fn serve_api_stats(metrics: &Metrics, model: Arc<RwLock<Model>>) -> http_types::Result<Response> {
     use serde::Serialize;
 
+    let route = "/api/stats";
+
     #[derive(Default, Serialize)]
     struct Stats {
         docs_count: usize,
@@ -69,7 +416,7 @@ fn serve_api_stats(model: Arc<Mutex<Model>>, request: Request) -> io::Result<()>
 
     let mut stats: Stats = Default::default();
     {
-        let model = model.lock().unwrap();
+        let model = model.read().await;
         stats.docs_count = model.docs.len();
         stats.terms_count = model.df.len();
     }
@@ -78,54 +425,220 @@ fn serve_api_stats(model: Arc<Mutex<Model>>, request: Request) -> io::Result<()>
         Ok(json) => json,
         Err(err) => {
             eprintln!("ERROR: could not convert stats results to JSON: {err}");
-            return serve_500(request);
+            return Ok(serve_500(metrics, route));
         }
     };
 
-    let content_type_header = Header::from_bytes("Content-Type", "application/json")
-        .expect("That we didn't put any garbage in the headers");
-    request.respond(Response::from_string(json).with_header(content_type_header))
+    metrics.observe_request(route, 200);
+    let mut response = Response::new(StatusCode::Ok);
+    response.set_content_type(http_types::mime::JSON);
+    response.set_body(json);
+    Ok(response)
+}
+
+async fn serve_metrics(metrics: &Metrics, model: Arc<RwLock<Model>>) -> http_types::Result<Response> {
+    let route = "/metrics";
+
+    {
+        let model = model.read().await;
+        metrics.docs_count.set(model.docs.len() as i64);
+        metrics.terms_count.set(model.df.len() as i64);
+    }
+
+    let metric_families = metrics.registry.gather();
+    let mut buf = Vec::new();
+    let encoder = TextEncoder::new();
+    if let Err(err) = encoder.encode(&metric_families, &mut buf) {
+        eprintln!("ERROR: could not encode Prometheus metrics: {err}");
+        return Ok(serve_500(metrics, route));
+    }
+
+    metrics.observe_request(route, 200);
+    let mut response = Response::new(StatusCode::Ok);
+    response.insert_header("Content-Type", "text/plain; version=0.0.4");
+    response.set_body(buf);
+    Ok(response)
 }
 
-fn serve_request(model: Arc<Mutex<Model>>, request: Request) -> io::Result<()> {
+async fn serve_request(
+    metrics: &Metrics,
+    model: Arc<RwLock<Model>>,
+    federation: &FederationConfig,
+    request: http_types::Request,
+) -> http_types::Result<Response> {
     println!(
         "INFO: received request! method: {:?}, url: {:?}",
         request.method(),
-        request.url()
+        request.url().path()
     );
 
-    match (request.method(), request.url()) {
-        (Method::Post, "/api/search") => serve_api_search(model, request),
-        (Method::Get, "/api/stats") => serve_api_stats(model, request),
-        (Method::Get, "/index.js") => serve_bytes(
-            request,
+    match (request.method(), request.url().path()) {
+        (Method::Post, "/api/search") => serve_api_search(metrics, model, request).await,
+        (Method::Post, "/api/fsearch") => serve_api_fsearch(metrics, model, federation, request).await,
+        (Method::Post, "/api/batch-search") => serve_api_batch_search(metrics, model, request).await,
+        (Method::Get, "/api/stats") => serve_api_stats(metrics, model).await,
+        (Method::Get, "/metrics") => serve_metrics(metrics, model).await,
+        (Method::Get, "/index.js") => Ok(serve_bytes(
+            metrics,
+            "/index.js",
             include_bytes!("index.js"),
             "text/javascript; charset=utf-8",
-        ),
-        (Method::Get, "/") | (Method::Get, "/index.html") => serve_bytes(
-            request,
+        )),
+        (Method::Get, "/") | (Method::Get, "/index.html") => Ok(serve_bytes(
+            metrics,
+            "/",
             include_bytes!("index.html"),
             "text/html; charset=utf-8",
-        ),
-        _ => serve_404(request),
+        )),
+        _ => Ok(serve_404(metrics, "unknown")),
+    }
+}
+
+async fn accept_connection(
+    stream: TcpStream,
+    model: Arc<RwLock<Model>>,
+    metrics: Arc<Metrics>,
+    federation: Arc<FederationConfig>,
+) {
+    let result = async_h1::accept(stream, |request| {
+        let model = Arc::clone(&model);
+        let metrics = Arc::clone(&metrics);
+        let federation = Arc::clone(&federation);
+        async move { serve_request(&metrics, model, &federation, request).await }
+    })
+    .await;
+
+    if let Err(err) = result {
+        eprintln!("ERROR: could not serve the response: {err}");
     }
 }
 
-pub fn start(address: &str, model: Arc<Mutex<Model>>) -> Result<(), ()> {
-    let server = Server::http(address).map_err(|err| {
-        eprintln!("ERROR: could not start HTTP server at {address}: {err}");
-    })?;
+/// Listens for connections and serves each one on its own task, so that a
+/// slow search no longer blocks every other client behind it. Route handlers
+/// take a shared read lock on the model, letting searches and stats lookups
+/// run truly concurrently.
+pub fn start(
+    address: &str,
+    model: Arc<RwLock<Model>>,
+    federation: FederationConfig,
+) -> Result<(), ()> {
+    task::block_on(async move {
+        let listener = TcpListener::bind(address).await.map_err(|err| {
+            eprintln!("ERROR: could not start HTTP server at {address}: {err}");
+        })?;
 
-    println!("INFO: listening at http://{address}/");
+        let metrics = Arc::new(Metrics::new());
+        let federation = Arc::new(federation);
 
-    for request in server.incoming_requests() {
-        serve_request(Arc::clone(&model), request)
-            .map_err(|err| {
-                eprintln!("ERROR: could not serve the response: {err}");
-            })
-            .ok(); // <- don't stop on errors, keep serving
+        println!("INFO: listening at http://{address}/");
+
+        let mut incoming = listener.incoming();
+        while let Some(stream) = incoming.next().await {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("ERROR: could not accept connection: {err}");
+                    continue;
+                }
+            };
+
+            task::spawn(accept_connection(
+                stream,
+                Arc::clone(&model),
+                Arc::clone(&metrics),
+                Arc::clone(&federation),
+            ));
+        }
+
+        eprintln!("ERROR: the server socket has shutdown");
+        Err(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_pagination_defaults() {
+        assert_eq!(resolve_pagination(None, None), (DEFAULT_SEARCH_LIMIT, 0));
+    }
+
+    #[test]
+    fn resolve_pagination_clamps_limit_to_max() {
+        assert_eq!(
+            resolve_pagination(Some(MAX_SEARCH_LIMIT + 1000), None),
+            (MAX_SEARCH_LIMIT, 0)
+        );
+    }
+
+    #[test]
+    fn resolve_pagination_honors_explicit_values() {
+        assert_eq!(resolve_pagination(Some(5), Some(10)), (5, 10));
+    }
+
+    #[test]
+    fn csv_quote_leaves_plain_field_untouched() {
+        assert_eq!(csv_quote("docs/readme.txt"), "docs/readme.txt");
+    }
+
+    #[test]
+    fn csv_quote_wraps_field_containing_a_comma() {
+        assert_eq!(csv_quote("notes, final.txt"), "\"notes, final.txt\"");
+    }
+
+    #[test]
+    fn csv_quote_doubles_embedded_quotes() {
+        assert_eq!(csv_quote("a \"b\" c"), "\"a \"\"b\"\" c\"");
+    }
+
+    #[test]
+    fn csv_quote_wraps_field_containing_a_newline() {
+        assert_eq!(csv_quote("a\nb"), "\"a\nb\"");
+    }
+
+    fn sample_page() -> Vec<(std::path::PathBuf, f32)> {
+        vec![(std::path::PathBuf::from("a.txt"), 0.5)]
+    }
+
+    #[test]
+    fn serialize_search_results_defaults_to_json() {
+        let owned = sample_page();
+        let page = owned.iter().collect::<Vec<_>>();
+        let (bytes, content_type) = serialize_search_results(None, &page, 1);
+        assert_eq!(content_type, "application/json");
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["results"][0]["path"], "a.txt");
+        assert_eq!(body["results"][0]["score"], 0.5);
+        assert_eq!(body["total"], 1);
+    }
+
+    #[test]
+    fn serialize_search_results_picks_csv() {
+        let owned = sample_page();
+        let page = owned.iter().collect::<Vec<_>>();
+        let (bytes, content_type) = serialize_search_results(Some("text/csv"), &page, 1);
+        assert_eq!(content_type, "text/csv");
+        assert_eq!(String::from_utf8(bytes).unwrap(), "path,score\na.txt,0.5\n");
+    }
+
+    #[test]
+    fn serialize_search_results_picks_ndjson() {
+        let owned = sample_page();
+        let page = owned.iter().collect::<Vec<_>>();
+        let (bytes, content_type) = serialize_search_results(Some("application/x-ndjson"), &page, 1);
+        assert_eq!(content_type, "application/x-ndjson");
+        let line: serde_json::Value =
+            serde_json::from_str(String::from_utf8(bytes).unwrap().trim()).unwrap();
+        assert_eq!(line["path"], "a.txt");
+        assert_eq!(line["score"], 0.5);
     }
 
-    eprintln!("ERROR: the server socket has shutdown");
-    Err(())
+    #[test]
+    fn serialize_search_results_falls_back_to_json_for_unknown_accept() {
+        let owned = sample_page();
+        let page = owned.iter().collect::<Vec<_>>();
+        let (_, content_type) = serialize_search_results(Some("text/plain"), &page, 1);
+        assert_eq!(content_type, "application/json");
+    }
 }
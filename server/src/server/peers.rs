@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+fn default_peer_timeout_ms() -> u64 {
+    2000
+}
+
+/// A remote seroost node to fan queries out to.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PeerConfig {
+    pub url: String,
+    #[serde(default = "default_peer_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+/// Federation-wide settings threaded into [`crate::server::start`].
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FederationConfig {
+    pub peers: Vec<PeerConfig>,
+}
+
+/// A single hit returned by a peer, normalized into `[0, 1]` and tagged with
+/// the node it came from.
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerHit {
+    pub path: String,
+    pub score: f32,
+    pub node: String,
+}
+
+#[derive(Deserialize)]
+struct PeerSearchHit {
+    path: String,
+    score: f32,
+}
+
+#[derive(Deserialize)]
+struct PeerSearchResponse {
+    results: Vec<PeerSearchHit>,
+}
+
+/// POSTs `query` to a single peer's `/api/search` and normalizes its hits to
+/// `[0, 1]` by dividing by that peer's own top score, so that peers with
+/// larger or smaller corpora don't dominate the merged ranking.
+pub fn query_peer(peer: &PeerConfig, query: &str, limit: usize) -> Result<Vec<PeerHit>, ureq::Error> {
+    let url = format!("{}/api/search", peer.url.trim_end_matches('/'));
+
+    let response: PeerSearchResponse = ureq::post(&url)
+        .timeout(Duration::from_millis(peer.timeout_ms))
+        .send_json(serde_json::json!({ "query": query, "limit": limit }))?
+        .into_json()?;
+
+    let top_score = response
+        .results
+        .iter()
+        .map(|hit| hit.score)
+        .fold(0.0f32, f32::max);
+
+    Ok(response
+        .results
+        .into_iter()
+        .map(|PeerSearchHit { path, score }| PeerHit {
+            path,
+            score: if top_score > 0.0 { score / top_score } else { 0.0 },
+            node: peer.url.clone(),
+        })
+        .collect())
+}
+
+/// Queries every configured peer concurrently, skipping (and logging) any
+/// that fail, and returns the combined list of normalized hits.
+///
+/// `query_peer` is a blocking call (`ureq` does its own synchronous
+/// DNS/connect/read), so each one runs on `async_std`'s blocking thread pool
+/// via `spawn_blocking` rather than on the async executor's worker threads --
+/// otherwise a handful of concurrent `/api/fsearch` requests would stall
+/// every other route behind them.
+pub async fn query_peers(peers: &[PeerConfig], query: &str, limit: usize) -> Vec<PeerHit> {
+    let tasks = peers.iter().cloned().map(|peer| {
+        let query = query.to_string();
+        async_std::task::spawn_blocking(move || {
+            let result = query_peer(&peer, &query, limit);
+            (peer, result)
+        })
+    });
+
+    let mut hits = Vec::new();
+    for (peer, result) in futures::future::join_all(tasks).await {
+        match result {
+            Ok(peer_hits) => hits.extend(peer_hits),
+            Err(err) => {
+                eprintln!("WARNING: peer {} request failed: {err}", peer.url);
+            }
+        }
+    }
+    hits
+}
+
+/// Deduplicates hits by path (keeping the max normalized score), sorts them
+/// descending, and truncates to `limit`.
+pub fn merge_peer_hits(peer_hits: Vec<PeerHit>, limit: usize) -> Vec<PeerHit> {
+    let mut best: HashMap<String, PeerHit> = HashMap::new();
+    for hit in peer_hits {
+        best.entry(hit.path.clone())
+            .and_modify(|existing| {
+                if hit.score > existing.score {
+                    *existing = hit.clone();
+                }
+            })
+            .or_insert(hit);
+    }
+
+    let mut merged: Vec<PeerHit> = best.into_values().collect();
+    merged.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    merged.truncate(limit);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(path: &str, score: f32, node: &str) -> PeerHit {
+        PeerHit {
+            path: path.to_string(),
+            score,
+            node: node.to_string(),
+        }
+    }
+
+    #[test]
+    fn merge_peer_hits_empty_input() {
+        assert!(merge_peer_hits(Vec::new(), 10).is_empty());
+    }
+
+    #[test]
+    fn merge_peer_hits_keeps_max_score_for_duplicate_paths() {
+        let merged = merge_peer_hits(
+            vec![
+                hit("a.txt", 0.4, "node-1"),
+                hit("a.txt", 0.9, "node-2"),
+                hit("a.txt", 0.1, "node-3"),
+            ],
+            10,
+        );
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].path, "a.txt");
+        assert_eq!(merged[0].score, 0.9);
+        assert_eq!(merged[0].node, "node-2");
+    }
+
+    #[test]
+    fn merge_peer_hits_sorts_descending_and_truncates() {
+        let merged = merge_peer_hits(
+            vec![
+                hit("low.txt", 0.1, "node-1"),
+                hit("high.txt", 0.9, "node-1"),
+                hit("mid.txt", 0.5, "node-1"),
+            ],
+            2,
+        );
+
+        assert_eq!(
+            merged.iter().map(|hit| hit.path.as_str()).collect::<Vec<_>>(),
+            vec!["high.txt", "mid.txt"]
+        );
+    }
+
+    #[test]
+    fn merge_peer_hits_keeps_tied_scores_without_panicking() {
+        let merged = merge_peer_hits(
+            vec![hit("a.txt", 0.5, "node-1"), hit("b.txt", 0.5, "node-2")],
+            10,
+        );
+
+        assert_eq!(merged.len(), 2);
+        assert!(merged.iter().all(|hit| hit.score == 0.5));
+    }
+}